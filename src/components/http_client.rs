@@ -0,0 +1,112 @@
+use http::{HeaderMap, HeaderName, HeaderValue, Request};
+use opentelemetry::{global, propagation::Injector};
+use tracing::{field, info_span, span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl<'a> Injector for HeaderInjector<'a> {
+    /// Set a key and value in the HeaderMap. Does nothing if the key or value are not valid inputs.
+    fn set(&mut self, key: &str, value: String) {
+        if let Ok(name) = HeaderName::from_bytes(key.as_bytes()) {
+            if let Ok(val) = HeaderValue::from_str(&value) {
+                self.0.insert(name, val);
+            }
+        }
+    }
+}
+
+/// 將 current span 的 context 注入到 outgoing HTTP 的 header map 中
+///
+/// 用於自行發送 HTTP 請求時手動傳遞 trace context，與 tonic 的
+/// `create_request_with_span` 對應。
+pub fn inject_current_span(headers: &mut HeaderMap) {
+    let cx: opentelemetry::Context = span::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut HeaderInjector(headers))
+    });
+}
+
+/// 為一個 outgoing HTTP request 建立 client span，名稱由 method + host 推導而來。
+fn make_client_span<T>(request: &Request<T>) -> Span {
+    let method = request.method().as_str();
+    let host = request.uri().host().unwrap_or_default();
+    info_span!(
+        "outgoing request",
+        trace_id = field::Empty,
+        span_id = field::Empty,
+        otel.kind = "client",
+        otel.name = format!("{} {}", method, host),
+    )
+}
+
+/// `tower::Layer`，讓 reqwest-middleware / tower 為基礎的 client 自動產生
+/// child client span 並注入 trace context，使其與 server span 串接。
+#[derive(Debug, Clone, Default)]
+pub struct TraceClientLayer;
+
+impl<S> tower::Layer<S> for TraceClientLayer {
+    type Service = TraceClientService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceClientService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceClientService<S> {
+    inner: S,
+}
+
+type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+impl<S, B> tower::Service<Request<B>> for TraceClientService<S>
+where
+    S: tower::Service<Request<B>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let span = make_client_span(&req);
+
+        // inject the client span context into the outgoing headers.
+        {
+            let _enter = span.enter();
+            inject_current_span(req.headers_mut());
+        }
+
+        let fut = self.inner.call(req).instrument(span);
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_span_registers_trace_and_span_id_fields() {
+        let request = Request::builder()
+            .method(http::Method::GET)
+            .uri("http://example.com/api")
+            .body(())
+            .unwrap();
+        let span = make_client_span(&request);
+        let fields = span.metadata().unwrap().fields();
+
+        // both placeholders must exist so `TraceIdLayer` can fill them in.
+        assert!(fields.field("trace_id").is_some());
+        assert!(fields.field("span_id").is_some());
+    }
+}
@@ -1,4 +1,5 @@
 pub mod base_metrics;
+pub mod http_client;
 mod std_log_formatter;
 mod telemetry_initializer;
 pub mod tonic;
@@ -6,3 +7,7 @@ pub mod tonic;
 pub use base_metrics::base_metrics;
 pub use std_log_formatter::ConsoleLogLayer;
 pub use telemetry_initializer::Builder as TelemetryBuilder;
+pub use telemetry_initializer::LogRotation;
+pub use telemetry_initializer::OtlpProtocol;
+pub use telemetry_initializer::PrometheusHandle;
+pub use telemetry_initializer::TelemetryGuard;
@@ -31,7 +31,8 @@ use crate::enums::LogLevel;
 /// use common_lib::components::telemetry_initializer::Builder;
 /// use common_lib::enums::LogLevel;
 ///
-/// Builder::new("service_name")
+/// // build() 回傳的 TelemetryGuard 需持有到程式結束，確保最後一批資料被 flush
+/// let _guard = Builder::new("service_name")
 ///    .set_log_level(LogLevel::Debug) // 如果不加這行預設為 info
 ///    .enable_tracing("http://localhost:4317") // 如果不要啟動 tracing 就不要加這行
 ///    .enable_metrics("http://localhost:4317") // 如果不要啟動 metrics 就不要加這行
@@ -43,20 +44,81 @@ use crate::enums::LogLevel;
 /// - 如果設定的 url 有誤會panic
 pub struct Builder<'a> {
     service_name: &'a str,
+    service_version: Option<&'a str>,
+    environment: Option<&'a str>,
     log_level: LogLevel,
+    otlp_protocol: OtlpProtocol,
     trace_export_url: Option<&'a str>,
     metrics_export_url: Option<&'a str>,
+    prometheus_metrics: bool,
     loki_export_url: Option<&'a str>,
+    file_log: Option<(&'a str, LogRotation)>,
+    sampling_ratio: Option<f64>,
+}
+
+/// rolling file log 的輪替週期，對應 `tracing_appender` 的 `Rotation`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    /// 每分鐘一個檔案。
+    Minutely,
+    /// 每小時一個檔案。
+    Hourly,
+    /// 每天一個檔案。
+    Daily,
+    /// 不輪替，全部寫入同一個檔案。
+    Never,
+}
+
+impl From<LogRotation> for tracing_appender::rolling::Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => tracing_appender::rolling::Rotation::MINUTELY,
+            LogRotation::Hourly => tracing_appender::rolling::Rotation::HOURLY,
+            LogRotation::Daily => tracing_appender::rolling::Rotation::DAILY,
+            LogRotation::Never => tracing_appender::rolling::Rotation::NEVER,
+        }
+    }
+}
+
+/// OTLP exporter 的傳輸協定，供 tracer 與 metrics 共用。
+///
+/// gRPC 走 4317，HTTP/protobuf 與 HTTP/JSON 走 4318；某些環境只開放
+/// HTTP OTLP endpoint（或封鎖 gRPC），此時可切換成 `HttpBinary`/`HttpJson`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    /// OTLP over gRPC（tonic），預設值。
+    Grpc,
+    /// OTLP over HTTP，payload 為 protobuf。
+    HttpBinary,
+    /// OTLP over HTTP，payload 為 JSON。
+    HttpJson,
+}
+
+impl OtlpProtocol {
+    /// 對應到 `opentelemetry_otlp` 的 `Protocol`。
+    fn as_protocol(self) -> Protocol {
+        match self {
+            OtlpProtocol::Grpc => Protocol::Grpc,
+            OtlpProtocol::HttpBinary => Protocol::HttpBinary,
+            OtlpProtocol::HttpJson => Protocol::HttpJson,
+        }
+    }
 }
 
 impl<'a> Builder<'a> {
     pub fn new(service_name: &'a str) -> Self {
         Self {
             service_name,
+            service_version: None,
+            environment: None,
             log_level: LogLevel::Info,
+            otlp_protocol: OtlpProtocol::Grpc,
             trace_export_url: None,
             metrics_export_url: None,
+            prometheus_metrics: false,
             loki_export_url: None,
+            file_log: None,
+            sampling_ratio: None,
         }
     }
 
@@ -67,6 +129,51 @@ impl<'a> Builder<'a> {
         }
     }
 
+    /// 設定服務版本 (`service.version`)，會寫入所有 signal 的 resource。
+    pub fn set_service_version(self, version: &'a str) -> Self {
+        Self {
+            service_version: Some(version),
+            ..self
+        }
+    }
+
+    /// 設定部署環境 (`deployment.environment`，例如 `production`/`staging`)，
+    /// 會寫入所有 signal 的 resource。
+    pub fn set_environment(self, environment: &'a str) -> Self {
+        Self {
+            environment: Some(environment),
+            ..self
+        }
+    }
+
+    /// 設定 OTLP exporter 使用的傳輸協定（gRPC 或 HTTP）。
+    ///
+    /// 預設為 [`OtlpProtocol::Grpc`]；切換成 HTTP 時會同時套用到 tracer 與
+    /// metrics 的 exporter。
+    pub fn set_otlp_protocol(self, protocol: OtlpProtocol) -> Self {
+        Self {
+            otlp_protocol: protocol,
+            ..self
+        }
+    }
+
+    /// 設定 trace 取樣比例（0.0 ~ 1.0）。
+    ///
+    /// 會套用 `ParentBased(TraceIdRatioBased(ratio))`：在沒有上游 parent 時
+    /// 依 `ratio` 機率取樣，若有被 propagate 進來的 parent（axum/tonic ingress
+    /// 會呼叫 `set_parent`），則沿用上游的取樣決策，確保整條 trace 的一致性。
+    /// 不呼叫此方法時維持預設的 always-on 取樣。
+    pub fn set_sampling_ratio(self, ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "sampling ratio must be within 0.0..=1.0, got {ratio}"
+        );
+        Self {
+            sampling_ratio: Some(ratio),
+            ..self
+        }
+    }
+
     pub fn enable_tracing(self, export_url: &'a str) -> Self {
         Self {
             trace_export_url: Some(export_url),
@@ -81,6 +188,17 @@ impl<'a> Builder<'a> {
         }
     }
 
+    /// 啟用 pull-based 的 Prometheus metrics exporter，與 `enable_metrics`
+    /// 的 OTLP push 互斥；可透過 `build` 回傳之 guard 的
+    /// [`TelemetryGuard::prometheus_handle`] 取得 [`PrometheusHandle`]，供使用者
+    /// 掛載 `/metrics` endpoint。
+    pub fn enable_prometheus_metrics(self) -> Self {
+        Self {
+            prometheus_metrics: true,
+            ..self
+        }
+    }
+
     pub fn enable_log(self, export_url: &'a str) -> Self {
         Self {
             loki_export_url: Some(export_url),
@@ -88,7 +206,25 @@ impl<'a> Builder<'a> {
         }
     }
 
-    pub fn build(self) {
+    /// 啟用本地的 rolling file log，作為 console/OTLP/Loki 之外的
+    /// durable 紀錄；當 Loki 無法連線時仍可保留離線 log。
+    ///
+    /// `dir` 為 log 檔存放目錄，`rotation` 決定輪替週期。寫入透過
+    /// `tracing-appender` 的 non-blocking writer，其 `WorkerGuard` 會交由
+    /// `build()` 回傳的 [`TelemetryGuard`] 持有，確保結束時緩衝的 log 會被 flush。
+    pub fn enable_file_log(self, dir: &'a str, rotation: LogRotation) -> Self {
+        Self {
+            file_log: Some((dir, rotation)),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> TelemetryGuard {
+        // metrics 只能擇一: OTLP push 或 Prometheus pull，不可同時啟用。
+        if self.metrics_export_url.is_some() && self.prometheus_metrics {
+            panic!("cannot enable both OTLP-push and Prometheus-pull metrics at the same time.");
+        }
+
         // init tracing
         let trace_layer = if let Some(trace_export_url) = self.trace_export_url {
             let tracer = self
@@ -100,24 +236,48 @@ impl<'a> Builder<'a> {
         };
 
         // init loki
-        let loki_layer = if let Some(loki_export_url) = self.loki_export_url {
+        let (loki_layer, loki_task) = if let Some(loki_export_url) = self.loki_export_url {
             let (loki_layer, upload_log_task) = self
                 .init_loki(loki_export_url)
                 .expect("Failed to init Loki.");
-            let _loki_task: tokio::task::JoinHandle<()> = tokio::spawn(upload_log_task);
-            Some(loki_layer)
+            let task: tokio::task::JoinHandle<()> = tokio::spawn(upload_log_task);
+            (Some(loki_layer), Some(task))
         } else {
-            None
+            (None, None)
         };
 
-        // init metrics
-        let _metrics_provider = if let Some(metrics_export_url) = self.metrics_export_url {
-            Some(
-                self.init_metrics(metrics_export_url)
-                    .expect("Failed to initialize OpenTelemetry metrics."),
-            )
+        // init metrics (OTLP push 或 Prometheus pull)
+        let (metrics_provider, prometheus_handle) =
+            if let Some(metrics_export_url) = self.metrics_export_url {
+                let provider = self
+                    .init_metrics(metrics_export_url)
+                    .expect("Failed to initialize OpenTelemetry metrics.");
+                opentelemetry::global::set_meter_provider(provider.clone());
+                (Some(provider), None)
+            } else if self.prometheus_metrics {
+                let (provider, handle) = self
+                    .init_prometheus_metrics()
+                    .expect("Failed to initialize Prometheus metrics.");
+                opentelemetry::global::set_meter_provider(provider.clone());
+                (Some(provider), Some(handle))
+            } else {
+                (None, None)
+            };
+
+        // init rolling file log
+        let (file_layer, file_guard) = if let Some((dir, rotation)) = self.file_log {
+            let file_appender = tracing_appender::rolling::RollingFileAppender::new(
+                rotation.into(),
+                dir,
+                format!("{}.log", self.service_name),
+            );
+            let (non_blocking, worker_guard) = tracing_appender::non_blocking(file_appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(non_blocking);
+            (Some(layer), Some(worker_guard))
         } else {
-            None
+            (None, None)
         };
 
         // set log level
@@ -130,60 +290,178 @@ impl<'a> Builder<'a> {
             .with(super::ConsoleLogLayer)
             .with(TraceIdLayer)
             .with(trace_layer)
-            .with(loki_layer);
+            .with(loki_layer)
+            .with(file_layer);
 
         // Set the global subscriber
         tracing::subscriber::set_global_default(subscriber)
             .expect("Failed to set global subscriber.");
+
+        TelemetryGuard {
+            meter_provider: metrics_provider,
+            loki_task,
+            prometheus_handle,
+            file_guard,
+        }
+    }
+
+    /// 建立一份共用的 `Resource`，讓 tracer 與 meter 都帶上一致、可查詢的
+    /// metadata（以 OpenTelemetry semantic conventions 為準）。
+    /// Loki 不使用此 `Resource`：`init_loki` 以自己的 label（`service_name`、`host`）
+    /// 與 extra field（`process_id`、`service_version`、`environment`）帶上同樣的資訊，
+    /// 名稱並非 semantic conventions 的 key。
+    fn build_resource(&self) -> Resource {
+        use opentelemetry_semantic_conventions::resource;
+
+        let hostname = gethostname::gethostname()
+            .to_string_lossy()
+            .into_owned();
+
+        let mut attributes = vec![
+            KeyValue::new(resource::SERVICE_NAME, self.service_name.to_string()),
+            KeyValue::new(resource::HOST_NAME, hostname),
+            KeyValue::new(resource::PROCESS_PID, process::id() as i64),
+        ];
+        if let Some(version) = self.service_version {
+            attributes.push(KeyValue::new(resource::SERVICE_VERSION, version.to_string()));
+        }
+        if let Some(environment) = self.environment {
+            attributes.push(KeyValue::new(
+                resource::DEPLOYMENT_ENVIRONMENT,
+                environment.to_string(),
+            ));
+        }
+
+        Resource::new(attributes)
     }
 
     fn init_tracer(&self, export_url: &'a str) -> Result<sdktrace::Tracer, TraceError> {
+        self.validate_endpoint(export_url);
         opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
-        opentelemetry_otlp::new_pipeline()
-            .tracing()
-            .with_exporter(
+        let pipeline = opentelemetry_otlp::new_pipeline().tracing();
+        let pipeline = match self.otlp_protocol {
+            OtlpProtocol::Grpc => pipeline.with_exporter(
                 opentelemetry_otlp::new_exporter()
                     .tonic()
                     .with_endpoint(export_url),
-            )
-            .with_trace_config(sdktrace::config().with_resource(Resource::new(vec![
-                KeyValue::new("service.name", self.service_name.to_string()),
-            ])))
+            ),
+            OtlpProtocol::HttpBinary | OtlpProtocol::HttpJson => pipeline.with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(export_url)
+                    .with_protocol(self.otlp_protocol.as_protocol()),
+            ),
+        };
+        let mut trace_config = sdktrace::config().with_resource(self.build_resource());
+        if let Some(ratio) = self.sampling_ratio {
+            trace_config = trace_config.with_sampler(sdktrace::Sampler::ParentBased(Box::new(
+                sdktrace::Sampler::TraceIdRatioBased(ratio),
+            )));
+        }
+        pipeline
+            .with_trace_config(trace_config)
             .install_batch(runtime::Tokio)
     }
 
+    /// 依照所選的 [`OtlpProtocol`] 檢查 endpoint 的 scheme/port 是否合理，
+    /// 以便在設定錯誤時盡早 panic（而不是等到送資料才失敗）。
+    fn validate_endpoint(&self, export_url: &str) {
+        let url = Url::parse(export_url)
+            .unwrap_or_else(|err| panic!("invalid OTLP endpoint `{export_url}`: {err}"));
+
+        match self.otlp_protocol {
+            OtlpProtocol::Grpc => {
+                if !matches!(url.scheme(), "http" | "https" | "grpc") {
+                    panic!(
+                        "OTLP/gRPC endpoint `{export_url}` must use an http(s)/grpc scheme (default port 4317)."
+                    );
+                }
+                if url.port() == Some(4318) {
+                    panic!(
+                        "OTLP/gRPC endpoint `{export_url}` points at the HTTP port 4318; gRPC default is 4317."
+                    );
+                }
+            }
+            OtlpProtocol::HttpBinary | OtlpProtocol::HttpJson => {
+                if !matches!(url.scheme(), "http" | "https") {
+                    panic!(
+                        "OTLP/HTTP endpoint `{export_url}` must use an http(s) scheme (default port 4318)."
+                    );
+                }
+                if url.port() == Some(4317) {
+                    panic!(
+                        "OTLP/HTTP endpoint `{export_url}` points at the gRPC port 4317; HTTP default is 4318."
+                    );
+                }
+            }
+        }
+    }
+
     fn init_loki(
         &self,
         export_url: &'a str,
     ) -> Result<(Layer, BackgroundTask), tracing_loki::Error> {
-        let (layer, task) = tracing_loki::builder()
+        let hostname = gethostname::gethostname()
+            .to_string_lossy()
+            .into_owned();
+
+        let mut builder = tracing_loki::builder()
             .label("service_name", self.service_name)?
-            .extra_field("process_id", format!("{}", process::id()))?
-            .build_url(Url::parse(export_url).unwrap())?;
+            .label("host", hostname)?
+            .extra_field("process_id", format!("{}", process::id()))?;
+        if let Some(version) = self.service_version {
+            builder = builder.extra_field("service_version", version)?;
+        }
+        if let Some(environment) = self.environment {
+            builder = builder.extra_field("environment", environment)?;
+        }
+
+        let (layer, task) = builder.build_url(Url::parse(export_url).unwrap())?;
         Ok((layer, task))
     }
 
+    fn init_prometheus_metrics(
+        &self,
+    ) -> Result<(SdkMeterProvider, PrometheusHandle), opentelemetry::metrics::MetricsError> {
+        let registry = prometheus::Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()?;
+
+        let provider = SdkMeterProvider::builder()
+            .with_reader(exporter)
+            .with_resource(self.build_resource())
+            .build();
+
+        Ok((provider, PrometheusHandle { registry }))
+    }
+
     fn init_metrics(
         &self,
         export_url: &'a str,
     ) -> Result<SdkMeterProvider, opentelemetry::metrics::MetricsError> {
+        self.validate_endpoint(export_url);
         let export_config = ExportConfig {
             endpoint: export_url.to_string(),
             timeout: Duration::from_secs(3),
-            protocol: Protocol::Grpc,
+            protocol: self.otlp_protocol.as_protocol(),
         };
 
-        opentelemetry_otlp::new_pipeline()
-            .metrics(opentelemetry_sdk::runtime::Tokio)
-            .with_exporter(
+        let pipeline = opentelemetry_otlp::new_pipeline().metrics(opentelemetry_sdk::runtime::Tokio);
+        let pipeline = match self.otlp_protocol {
+            OtlpProtocol::Grpc => pipeline.with_exporter(
                 opentelemetry_otlp::new_exporter()
                     .tonic()
                     .with_export_config(export_config),
-            )
-            .with_resource(Resource::new(vec![KeyValue::new(
-                "service_name",
-                self.service_name.to_string(),
-            )]))
+            ),
+            OtlpProtocol::HttpBinary | OtlpProtocol::HttpJson => pipeline.with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_export_config(export_config),
+            ),
+        };
+        pipeline
+            .with_resource(self.build_resource())
             .with_period(Duration::from_secs(3))
             .with_timeout(Duration::from_secs(10))
             .with_aggregation_selector(DefaultAggregationSelector::new())
@@ -192,21 +470,236 @@ impl<'a> Builder<'a> {
     }
 }
 
+/// 對外 expose Prometheus metrics 的 handle；啟用 `enable_prometheus_metrics`
+/// 時可由 [`TelemetryGuard::prometheus_handle`] 取得，供使用者掛載 `/metrics` endpoint。
+#[derive(Clone)]
+pub struct PrometheusHandle {
+    registry: prometheus::Registry,
+}
+
+impl PrometheusHandle {
+    /// 取得底層的 `prometheus::Registry`。
+    pub fn registry(&self) -> &prometheus::Registry {
+        &self.registry
+    }
+
+    /// 將目前收集到的 metric families 編碼成 Prometheus text exposition 格式，
+    /// 可直接作為 actix/axum `/metrics` handler 的回應 body。
+    pub fn render(&self) -> String {
+        use prometheus::Encoder;
+
+        let encoder = prometheus::TextEncoder::new();
+        let metric_families = self.registry.gather();
+        let mut buffer = Vec::new();
+        encoder.encode(&metric_families, &mut buffer).unwrap_or_default();
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// `build()` 回傳的 shutdown/flush 守衛。
+///
+/// 它持有 meter provider 與 Loki 上傳 task，並在 `Drop` 時
+/// flush/關閉 tracer provider、meter provider，以及結束 Loki task，
+/// 避免 process 結束時遺失最後一批尚未送出的 traces/metrics/logs。
+/// 對於短命的 CLI 或需要 graceful shutdown 的 server，請持有此 guard
+/// 直到程式結束，或顯式呼叫 [`TelemetryGuard::shutdown`]。
+pub struct TelemetryGuard {
+    meter_provider: Option<SdkMeterProvider>,
+    loki_task: Option<tokio::task::JoinHandle<()>>,
+    prometheus_handle: Option<PrometheusHandle>,
+    /// rolling file log 的 `WorkerGuard`，drop 時 flush 緩衝的 log 行。
+    file_guard: Option<tracing_appender::non_blocking::WorkerGuard>,
+}
+
+impl TelemetryGuard {
+    /// 關閉 Loki task 前最多等待的時間。
+    const LOKI_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// 若啟用了 `enable_prometheus_metrics`，取得對外掛載 `/metrics` 用的
+    /// [`PrometheusHandle`]。
+    pub fn prometheus_handle(&self) -> Option<&PrometheusHandle> {
+        self.prometheus_handle.as_ref()
+    }
+
+    /// 以 graceful 的方式關閉所有 telemetry pipeline：flush tracer 與 meter
+    /// provider，並在 [`LOKI_FLUSH_TIMEOUT`](Self::LOKI_FLUSH_TIMEOUT) 內等待
+    /// Loki task 把剩餘的 log 送出，逾時則 abort。呼叫後 `Drop` 不會重複執行。
+    pub async fn shutdown(mut self) {
+        opentelemetry::global::shutdown_tracer_provider();
+        if let Some(provider) = self.meter_provider.take() {
+            let _ = provider.shutdown();
+        }
+        if let Some(mut task) = self.loki_task.take() {
+            if tokio::time::timeout(Self::LOKI_FLUSH_TIMEOUT, &mut task).await.is_err() {
+                // 逾時仍未結束，abort 背景的 Loki task。
+                task.abort();
+            }
+        }
+        // 在 forget 前先 drop file guard，確保 rolling file 的緩衝被 flush。
+        drop(self.file_guard.take());
+        // 已經在此完成關閉，避免 `Drop` 再跑一次 shutdown 流程。
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        opentelemetry::global::shutdown_tracer_provider();
+        if let Some(provider) = self.meter_provider.take() {
+            let _ = provider.shutdown();
+        }
+        // Drop 無法 await，直接 abort 背景的 Loki task。需要 graceful flush 時
+        // 請改呼叫 `shutdown().await`。
+        if let Some(task) = self.loki_task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// marker inserted into a span's extensions once its real OTel ids have been
+/// recorded, so we don't record them again on every subsequent event.
+struct TraceIdRecorded;
+
 struct TraceIdLayer;
 
+impl TraceIdLayer {
+    /// record `trace_id`/`span_id` into the span's registered fields if the
+    /// span already owns a valid OpenTelemetry context.
+    fn record_otel_ids<S>(&self, id: &tracing::Id, ctx: &tracing_subscriber::layer::Context<'_, S>)
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        // only record once the ids are available and not yet stored.
+        if span.extensions().get::<TraceIdRecorded>().is_some() {
+            return;
+        }
+
+        let (trace_id, span_id) = {
+            let ext = span.extensions();
+            match ext.get::<tracing_opentelemetry::OtelData>() {
+                Some(data) => {
+                    use opentelemetry::trace::TraceContextExt;
+                    // a span with a valid parent context (a parent span, or one
+                    // set via `set_parent`) is exported under the parent's trace
+                    // id; `builder.trace_id` only holds the id of a root span.
+                    let parent_span = data.parent_cx.span();
+                    let parent = parent_span.span_context();
+                    let trace_id = if parent.is_valid() {
+                        Some(parent.trace_id())
+                    } else {
+                        data.builder.trace_id
+                    };
+                    (trace_id, data.builder.span_id)
+                }
+                None => return,
+            }
+        };
+
+        // defer until a sampler has assigned a real (non-zero) trace id.
+        let trace_id = match trace_id {
+            Some(trace_id) if trace_id != opentelemetry::trace::TraceId::INVALID => trace_id,
+            _ => return,
+        };
+        let span_id = match span_id {
+            Some(span_id) if span_id != opentelemetry::trace::SpanId::INVALID => span_id,
+            _ => return,
+        };
+
+        // record the 32-hex-char trace id and 16-hex-char span id into the
+        // span's `trace_id = field::Empty` placeholders.
+        let metadata = span.metadata();
+        let trace_id = trace_id.to_string();
+        let span_id = span_id.to_string();
+        let fields = metadata.fields();
+        let values = [
+            fields
+                .field("trace_id")
+                .map(|field| (field, &trace_id as &dyn tracing::Value)),
+            fields
+                .field("span_id")
+                .map(|field| (field, &span_id as &dyn tracing::Value)),
+        ];
+        let values: Vec<_> = values.into_iter().flatten().collect();
+        if values.is_empty() {
+            return;
+        }
+
+        let pairs: Vec<(&tracing::field::Field, Option<&dyn tracing::Value>)> =
+            values.iter().map(|(f, v)| (f, Some(*v))).collect();
+        let value_set = fields.value_set(&pairs);
+        tracing::dispatcher::get_default(|dispatch| {
+            dispatch.record(id, &tracing::span::Record::new(&value_set));
+        });
+
+        span.extensions_mut().insert(TraceIdRecorded);
+    }
+}
+
 impl<S> tracing_subscriber::Layer<S> for TraceIdLayer
 where
     S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
 {
-    fn on_new_span(
-        &self,
-        _: &tracing::span::Attributes<'_>,
-        id: &tracing::Id,
-        ctx: tracing_subscriber::layer::Context<'_, S>,
-    ) {
-        if let Some(span) = ctx.span(id) {
-            let trace_id = id.into_u64().to_string(); // 获取 trace_id
-            span.extensions_mut().insert(trace_id.clone()); // 将 trace_id 存储到 span 的扩展字段中
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        // record on the first event inside the span: by this point the ingress
+        // middleware has already called `set_parent`, so the OTel context (and
+        // thus the trace id) is stable and matches the exported trace.
+        if let Some(span) = ctx.event_span(event) {
+            self.record_otel_ids(&span.id(), &ctx);
         }
     }
+
+    fn on_close(&self, id: tracing::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        // catch spans that emitted no events but still own a valid context.
+        self.record_otel_ids(&id, &ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::Key;
+    use opentelemetry_semantic_conventions::resource as semconv;
+
+    #[test]
+    fn build_resource_sets_semantic_convention_keys() {
+        let resource = Builder::new("svc")
+            .set_service_version("1.2.3")
+            .set_environment("staging")
+            .build_resource();
+
+        assert_eq!(
+            resource.get(Key::from_static_str(semconv::SERVICE_NAME)),
+            Some("svc".into())
+        );
+        assert_eq!(
+            resource.get(Key::from_static_str(semconv::SERVICE_VERSION)),
+            Some("1.2.3".into())
+        );
+        assert_eq!(
+            resource.get(Key::from_static_str(semconv::DEPLOYMENT_ENVIRONMENT)),
+            Some("staging".into())
+        );
+        assert!(resource
+            .get(Key::from_static_str(semconv::HOST_NAME))
+            .is_some());
+        assert!(resource
+            .get(Key::from_static_str(semconv::PROCESS_PID))
+            .is_some());
+    }
+
+    #[test]
+    fn build_resource_omits_unset_optional_keys() {
+        let resource = Builder::new("svc").build_resource();
+
+        assert!(resource
+            .get(Key::from_static_str(semconv::SERVICE_VERSION))
+            .is_none());
+        assert!(resource
+            .get(Key::from_static_str(semconv::DEPLOYMENT_ENVIRONMENT))
+            .is_none());
+    }
 }
@@ -14,6 +14,7 @@ impl RootSpanBuilder for CustomRootSpanBuilder {
             request,
             cloud_provider = "localhost",
             trace_id = tracing::field::Empty,
+            span_id = tracing::field::Empty,
         )
     }
 
@@ -24,6 +24,7 @@ fn make_root_span(request: &Request<Body>) -> Span {
         "incoming request",
         ?headers,
         trace_id = field::Empty,
+        span_id = field::Empty,
         otel.kind = "server",
         otel.name = format!("{}/{}", service, method),
     )